@@ -0,0 +1,292 @@
+//! Component is the parsed definition of an OAM component.
+//!
+//! This module holds the pieces of that definition the Scylla workload
+//! builders (`crate::workload_type::workload_builder`) translate into
+//! Kubernetes API objects.
+
+use k8s_openapi::api::core::v1 as api;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use std::collections::BTreeMap;
+
+/// A listening port declared by a component.
+#[derive(Clone)]
+pub struct Port {
+    pub container_port: i32,
+    pub protocol: Option<String>,
+}
+
+impl Port {
+    /// Translate this port into a `ServicePort` for the component's Service.
+    pub fn to_service_port(&self) -> api::ServicePort {
+        api::ServicePort {
+            port: self.container_port,
+            protocol: self.protocol.clone(),
+            target_port: Some(IntOrString::Int(self.container_port)),
+            ..Default::default()
+        }
+    }
+}
+
+/// A storage volume declared by a component.
+///
+/// `StatefulSetBuilder` turns one of these per declared volume into a
+/// `volumeClaimTemplates` entry so each replica gets its own disk.
+#[derive(Clone)]
+pub struct StorageVolume {
+    /// Name of the volume; also the name of the generated claim template.
+    pub name: String,
+    /// Requested capacity, e.g. "10Gi".
+    pub size: String,
+    pub access_modes: Vec<String>,
+}
+
+impl StorageVolume {
+    /// Translate this volume into a `PersistentVolumeClaimSpec`.
+    pub fn to_claim_spec(&self) -> api::PersistentVolumeClaimSpec {
+        let mut requests = BTreeMap::new();
+        requests.insert("storage".to_string(), Quantity(self.size.clone()));
+        api::PersistentVolumeClaimSpec {
+            access_modes: Some(self.access_modes.clone()),
+            resources: Some(api::ResourceRequirements {
+                requests: Some(requests),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// The kind of check a component can declare for a liveness/readiness probe.
+#[derive(Clone)]
+pub enum HealthCheckAction {
+    /// An HTTP GET against `path` on `port`.
+    HttpGet { path: String, port: i32 },
+    /// A bare TCP dial against `port`.
+    TcpSocket { port: i32 },
+    /// A command run inside the container.
+    Exec { command: Vec<String> },
+}
+
+/// A component's health-check definition, translated into a `core::v1::Probe`
+/// when building its container.
+#[derive(Clone)]
+pub struct HealthCheck {
+    pub action: HealthCheckAction,
+    pub initial_delay_seconds: Option<i32>,
+    pub period_seconds: Option<i32>,
+    pub failure_threshold: Option<i32>,
+}
+
+impl HealthCheck {
+    /// Translate this health check into a `core::v1::Probe`.
+    pub fn to_probe(&self) -> api::Probe {
+        let mut probe = api::Probe {
+            initial_delay_seconds: self.initial_delay_seconds,
+            period_seconds: self.period_seconds,
+            failure_threshold: self.failure_threshold,
+            ..Default::default()
+        };
+        match &self.action {
+            HealthCheckAction::HttpGet { path, port } => {
+                probe.http_get = Some(api::HTTPGetAction {
+                    path: Some(path.clone()),
+                    port: IntOrString::Int(*port),
+                    ..Default::default()
+                });
+            }
+            HealthCheckAction::TcpSocket { port } => {
+                probe.tcp_socket = Some(api::TCPSocketAction {
+                    port: IntOrString::Int(*port),
+                    ..Default::default()
+                });
+            }
+            HealthCheckAction::Exec { command } => {
+                probe.exec = Some(api::ExecAction {
+                    command: Some(command.clone()),
+                });
+            }
+        }
+        probe
+    }
+}
+
+/// Component is the parsed definition of an OAM component, as needed by the
+/// Scylla workload builders.
+#[derive(Clone)]
+pub struct Component {
+    pub name: String,
+    pub image: String,
+    pub listening_ports: Vec<Port>,
+    pub storage_volumes: Vec<StorageVolume>,
+    pub liveness_check: Option<HealthCheck>,
+    pub readiness_check: Option<HealthCheck>,
+}
+
+impl Component {
+    /// This component's declared listening ports.
+    pub fn listening_ports(self) -> Vec<Port> {
+        self.listening_ports
+    }
+
+    /// This component's declared storage volumes.
+    pub fn storage_volumes(self) -> Vec<StorageVolume> {
+        self.storage_volumes
+    }
+
+    /// This component's liveness health check, translated into a `Probe`.
+    pub fn liveness_probe(&self) -> Option<api::Probe> {
+        self.liveness_check.as_ref().map(HealthCheck::to_probe)
+    }
+
+    /// This component's readiness health check, translated into a `Probe`.
+    pub fn readiness_probe(&self) -> Option<api::Probe> {
+        self.readiness_check.as_ref().map(HealthCheck::to_probe)
+    }
+
+    /// Build the pod spec for this component's container, using `restart_policy`
+    /// for the pod-level restart policy.
+    pub fn to_pod_spec_with_policy(self, restart_policy: String) -> api::PodSpec {
+        let ports = if self.listening_ports.is_empty() {
+            None
+        } else {
+            Some(
+                self.listening_ports
+                    .iter()
+                    .map(|port| api::ContainerPort {
+                        container_port: port.container_port,
+                        protocol: port.protocol.clone(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )
+        };
+        api::PodSpec {
+            restart_policy: Some(restart_policy),
+            containers: vec![api::Container {
+                name: self.name,
+                image: Some(self.image),
+                ports,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_service_port_targets_the_container_port() {
+        let port = Port {
+            container_port: 9042,
+            protocol: Some("TCP".to_string()),
+        };
+        let service_port = port.to_service_port();
+        assert_eq!(service_port.port, 9042);
+        assert_eq!(service_port.target_port, Some(IntOrString::Int(9042)));
+        assert_eq!(service_port.protocol, Some("TCP".to_string()));
+    }
+
+    #[test]
+    fn to_claim_spec_requests_the_declared_size() {
+        let volume = StorageVolume {
+            name: "data".to_string(),
+            size: "10Gi".to_string(),
+            access_modes: vec!["ReadWriteOnce".to_string()],
+        };
+        let spec = volume.to_claim_spec();
+        assert_eq!(spec.access_modes, Some(vec!["ReadWriteOnce".to_string()]));
+        let requests = spec.resources.unwrap().requests.unwrap();
+        assert_eq!(requests.get("storage"), Some(&Quantity("10Gi".to_string())));
+    }
+
+    #[test]
+    fn to_pod_spec_with_policy_sets_restart_policy_and_ports() {
+        let component = Component {
+            name: "scylla".to_string(),
+            image: "scylladb/scylla:5.2".to_string(),
+            listening_ports: vec![Port {
+                container_port: 9042,
+                protocol: Some("TCP".to_string()),
+            }],
+            storage_volumes: Vec::new(),
+            liveness_check: None,
+            readiness_check: None,
+        };
+        let pod_spec = component.to_pod_spec_with_policy("Always".to_string());
+        assert_eq!(pod_spec.restart_policy, Some("Always".to_string()));
+        assert_eq!(pod_spec.containers.len(), 1);
+        assert_eq!(
+            pod_spec.containers[0].ports.as_ref().unwrap()[0].container_port,
+            9042
+        );
+    }
+
+    #[test]
+    fn http_get_health_check_translates_to_an_http_get_probe() {
+        let check = HealthCheck {
+            action: HealthCheckAction::HttpGet {
+                path: "/healthz".to_string(),
+                port: 8080,
+            },
+            initial_delay_seconds: Some(5),
+            period_seconds: Some(10),
+            failure_threshold: Some(3),
+        };
+        let probe = check.to_probe();
+        assert_eq!(probe.initial_delay_seconds, Some(5));
+        assert_eq!(probe.period_seconds, Some(10));
+        assert_eq!(probe.failure_threshold, Some(3));
+        let http_get = probe.http_get.expect("expected an httpGet probe");
+        assert_eq!(http_get.path, Some("/healthz".to_string()));
+        assert_eq!(http_get.port, IntOrString::Int(8080));
+        assert!(probe.tcp_socket.is_none());
+        assert!(probe.exec.is_none());
+    }
+
+    #[test]
+    fn tcp_socket_health_check_translates_to_a_tcp_socket_probe() {
+        let check = HealthCheck {
+            action: HealthCheckAction::TcpSocket { port: 9042 },
+            initial_delay_seconds: None,
+            period_seconds: None,
+            failure_threshold: None,
+        };
+        let probe = check.to_probe();
+        let tcp_socket = probe.tcp_socket.expect("expected a tcpSocket probe");
+        assert_eq!(tcp_socket.port, IntOrString::Int(9042));
+        assert!(probe.http_get.is_none());
+        assert!(probe.exec.is_none());
+    }
+
+    #[test]
+    fn exec_health_check_translates_to_an_exec_probe() {
+        let check = HealthCheck {
+            action: HealthCheckAction::Exec {
+                command: vec![
+                    "cqlsh".to_string(),
+                    "-e".to_string(),
+                    "describe keyspaces".to_string(),
+                ],
+            },
+            initial_delay_seconds: None,
+            period_seconds: None,
+            failure_threshold: None,
+        };
+        let probe = check.to_probe();
+        let exec = probe.exec.expect("expected an exec probe");
+        assert_eq!(
+            exec.command,
+            Some(vec![
+                "cqlsh".to_string(),
+                "-e".to_string(),
+                "describe keyspaces".to_string()
+            ])
+        );
+        assert!(probe.http_get.is_none());
+        assert!(probe.tcp_socket.is_none());
+    }
+}
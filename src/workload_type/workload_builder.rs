@@ -1,9 +1,18 @@
+use k8s_openapi::api::apps::v1 as apps;
 use k8s_openapi::api::batch::v1 as batchapi;
 use k8s_openapi::api::core::v1 as api;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1 as meta;
-use kube::api::PostParams;
+use kube::api::{Patch, PatchParams, PostParams, Resource};
 use kube::client::APIClient;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use failure::format_err;
 
 use crate::schematic::component::Component;
 use crate::workload_type::{InstigatorResult, ParamMap};
@@ -32,10 +41,222 @@ pub struct WorkloadMetadata {
     /// This tells Kubenretes what object "owns" this workload and is responsible
     /// for cleaning it up.
     pub owner_ref: Option<Vec<meta::OwnerReference>>,
+    /// Liveness Probe is the probe Kubernetes uses to decide whether to restart
+    /// the workload's container.
+    pub liveness_probe: Option<api::Probe>,
+    /// Readiness Probe is the probe Kubernetes uses to decide whether the
+    /// workload's container is ready to receive traffic.
+    pub readiness_probe: Option<api::Probe>,
 }
 
 type Labels = BTreeMap<String, String>;
 
+/// Attach liveness/readiness probes (if any) to the first container in a pod spec.
+///
+/// Workload components only ever emit a single container today, so this
+/// mirrors the assumption already made throughout `to_pod_spec_with_policy`.
+fn with_probes(
+    mut pod_spec: api::PodSpec,
+    liveness_probe: Option<api::Probe>,
+    readiness_probe: Option<api::Probe>,
+) -> api::PodSpec {
+    if let Some(container) = pod_spec.containers.first_mut() {
+        container.liveness_probe = liveness_probe;
+        container.readiness_probe = readiness_probe;
+    }
+    pod_spec
+}
+
+/// Field manager name used when server-side-applying workload objects.
+const FIELD_MANAGER: &str = "rudr";
+
+/// Whether a previously-fetched object should be patched in place or created
+/// from scratch.
+#[derive(Debug, PartialEq, Eq)]
+enum SyncAction {
+    Create,
+    Patch,
+}
+
+/// Decide whether `existing` (the result of a `get_opt` lookup) means we
+/// should create a new object or patch the one already there.
+///
+/// Split out from `create_or_patch` so the branching that makes workload
+/// instantiation idempotent can be unit tested without a Kubernetes API.
+fn decide_sync_action<K>(existing: &Option<K>) -> SyncAction {
+    match existing {
+        Some(_) => SyncAction::Patch,
+        None => SyncAction::Create,
+    }
+}
+
+/// Create `obj` if it doesn't already exist, otherwise converge it in place
+/// with a server-side apply patch.
+///
+/// Workload instantiation needs to be idempotent: re-applying the same
+/// ApplicationConfiguration, or a reconcile re-run, must not fail with
+/// `AlreadyExists`. Using `get_opt` first lets us pick `create` vs `patch`
+/// uniformly for Jobs, Services and StatefulSets.
+async fn create_or_patch<K>(api: &kube::api::Api<K>, name: &str, obj: &K) -> InstigatorResult
+where
+    K: Resource + Clone + Debug + Serialize + DeserializeOwned,
+{
+    let existing = api.get_opt(name).await?;
+    match decide_sync_action(&existing) {
+        SyncAction::Patch => {
+            let pp = PatchParams::apply(FIELD_MANAGER);
+            api.patch(name, &pp, &Patch::Apply(obj)).await?;
+        }
+        SyncAction::Create => {
+            let pp = PostParams::default();
+            api.create(&pp, obj).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Wait for a freshly (re)created Job to have at least one active or
+/// succeeded pod.
+///
+/// This is what lets a `NodeConcurrencyLimiter` permit span the time a pod
+/// actually takes to schedule and start, rather than just the `create`/
+/// `patch` call, which returns long before that happens. Only called when a
+/// concurrency limiter is actually in play, since the poll has real latency
+/// that an un-gated workload has no reason to pay.
+async fn wait_for_job_started(api: &kube::api::Api<batchapi::Job>, name: &str) -> InstigatorResult {
+    for _ in 0..READY_POLL_ATTEMPTS {
+        if let Some(job) = api.get_opt(name).await? {
+            let status = job.status.unwrap_or_default();
+            if status.active.unwrap_or(0) > 0 || status.succeeded.unwrap_or(0) > 0 {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+    Err(format_err!(
+        "job {} did not report a started pod after {} attempts",
+        name,
+        READY_POLL_ATTEMPTS
+    ))
+}
+
+/// Wait for a freshly (re)created StatefulSet to report `desired_replicas`
+/// ready replicas, for the same reason `wait_for_job_started` waits on Jobs.
+/// Only called when a concurrency limiter is actually in play.
+async fn wait_for_stateful_set_ready(
+    api: &kube::api::Api<apps::StatefulSet>,
+    name: &str,
+    desired_replicas: i32,
+) -> InstigatorResult {
+    for _ in 0..READY_POLL_ATTEMPTS {
+        if let Some(sts) = api.get_opt(name).await? {
+            let status = sts.status.unwrap_or_default();
+            if status.ready_replicas.unwrap_or(0) >= desired_replicas {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+    Err(format_err!(
+        "stateful set {} did not reach {} ready replicas after {} attempts",
+        name,
+        desired_replicas,
+        READY_POLL_ATTEMPTS
+    ))
+}
+
+/// Default value for `concurrent-workloads-per-node-limit`.
+pub const DEFAULT_CONCURRENT_WORKLOADS_PER_NODE_LIMIT: usize = 5;
+
+/// Node key used when a workload doesn't pin itself to a specific node.
+const UNSCHEDULED_NODE: &str = "_unscheduled";
+
+/// How long to wait between polls while waiting for a workload to report
+/// that its pod(s) have actually started.
+const READY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How many times to poll a freshly (re)created workload for readiness
+/// before giving up, releasing its concurrency slot, and reporting the
+/// instantiation as failed, so a stuck workload can't wedge the limiter
+/// forever or be mistaken for a successful rollout.
+const READY_POLL_ATTEMPTS: usize = 150;
+
+/// Gates how many workload instantiations (Jobs and StatefulSets) this crate
+/// will run concurrently against any one node, so applying a large
+/// ApplicationConfiguration can't overwhelm a node during bulk restores or
+/// cold starts.
+///
+/// A permit is held from just before the object is created/patched until its
+/// pod(s) are observed to have actually started, not merely until the create
+/// API call returns, since that call completes long before a pod schedules
+/// and boots.
+///
+/// A `limit` of `0` pauses all workload instantiation: the per-node semaphore
+/// is created with zero permits, so `acquire` never returns.
+#[derive(Clone)]
+pub struct NodeConcurrencyLimiter {
+    limit: usize,
+    semaphores: Arc<Mutex<BTreeMap<String, Arc<Semaphore>>>>,
+}
+
+impl NodeConcurrencyLimiter {
+    /// Create a limiter gating at most `limit` concurrent instantiations per node.
+    pub fn new(limit: usize) -> Self {
+        NodeConcurrencyLimiter {
+            limit,
+            semaphores: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    fn semaphore_for(&self, node: &str) -> Arc<Semaphore> {
+        let mut semaphores = self.semaphores.lock().unwrap();
+        semaphores
+            .entry(node.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limit)))
+            .clone()
+    }
+
+    /// Wait until an instantiation slot against `node` is free, then hold it
+    /// until the returned permit is dropped.
+    pub async fn acquire(&self, node: &str) -> NodeConcurrencyPermit {
+        let semaphore = self.semaphore_for(node);
+        let permit = semaphore
+            .acquire_owned()
+            .await
+            .expect("node semaphore is never closed");
+        NodeConcurrencyPermit { permit }
+    }
+}
+
+/// A held concurrency slot for one node, released when dropped.
+pub struct NodeConcurrencyPermit {
+    permit: OwnedSemaphorePermit,
+}
+
+/// Valid values for `JobSpec.completion_mode`.
+///
+/// Kubernetes only recognizes these two strings; typing `JobBuilder::completion_mode`
+/// against this enum instead of a raw `String` keeps a typo like `"Indexd"` from
+/// flowing straight into the API server unchecked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionMode {
+    /// Pods don't get a stable per-pod completion index.
+    NonIndexed,
+    /// Each pod gets a stable completion index exposed via the
+    /// `batch.kubernetes.io/job-completion-index` downward API annotation,
+    /// which MPI-style Scylla workloads use to self-assign a role.
+    Indexed,
+}
+
+impl CompletionMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            CompletionMode::NonIndexed => "NonIndexed",
+            CompletionMode::Indexed => "Indexed",
+        }
+    }
+}
+
 /// JobBuilder builds new jobs specific to Scylla
 ///
 /// This hides many of the details of building a Job, exposing only
@@ -47,11 +268,26 @@ pub(crate) struct JobBuilder {
     restart_policy: String,
     owner_ref: Option<Vec<meta::OwnerReference>>,
     parallelism: Option<i32>,
+    liveness_probe: Option<api::Probe>,
+    readiness_probe: Option<api::Probe>,
+    completions: Option<i32>,
+    backoff_limit: Option<i32>,
+    active_deadline_seconds: Option<i64>,
+    completion_mode: Option<CompletionMode>,
+    ttl_seconds_after_finished: Option<i32>,
+    concurrency_limiter: Option<NodeConcurrencyLimiter>,
+    target_node: Option<String>,
 }
 
 impl JobBuilder {
     /// Create a JobBuilder
+    ///
+    /// Liveness and readiness probes default to whatever the component's
+    /// health-check definition translates to, and can be overridden with
+    /// `liveness_probe`/`readiness_probe`.
     pub fn new(instance_name: String, component: Component) -> Self {
+        let liveness_probe = component.liveness_probe();
+        let readiness_probe = component.readiness_probe();
         JobBuilder {
             name: instance_name,
             component: component,
@@ -59,6 +295,15 @@ impl JobBuilder {
             restart_policy: "Never".to_string(),
             owner_ref: None,
             parallelism: None,
+            liveness_probe,
+            readiness_probe,
+            completions: None,
+            backoff_limit: Some(4),
+            active_deadline_seconds: None,
+            completion_mode: None,
+            ttl_seconds_after_finished: None,
+            concurrency_limiter: None,
+            target_node: None,
         }
     }
     /// Add labels
@@ -71,6 +316,16 @@ impl JobBuilder {
         self.restart_policy = policy;
         self
     }
+    /// Gate this job's instantiation against `target_node`'s concurrency limit
+    pub fn concurrency_limiter(
+        mut self,
+        limiter: NodeConcurrencyLimiter,
+        target_node: String,
+    ) -> Self {
+        self.concurrency_limiter = Some(limiter);
+        self.target_node = Some(target_node);
+        self
+    }
     /// Set the owner refence for the job and the pod
     pub fn owner_ref(mut self, owner: Option<Vec<meta::OwnerReference>>) -> Self {
         self.owner_ref = owner;
@@ -81,6 +336,41 @@ impl JobBuilder {
         self.parallelism = Some(count);
         self
     }
+    /// Override the liveness probe attached to the job's container
+    pub fn liveness_probe(mut self, probe: Option<api::Probe>) -> Self {
+        self.liveness_probe = probe;
+        self
+    }
+    /// Override the readiness probe attached to the job's container
+    pub fn readiness_probe(mut self, probe: Option<api::Probe>) -> Self {
+        self.readiness_probe = probe;
+        self
+    }
+    /// Set the number of successful pod completions required to mark the job done
+    pub fn completions(mut self, count: i32) -> Self {
+        self.completions = Some(count);
+        self
+    }
+    /// Set the number of retries before the job is marked failed
+    pub fn backoff_limit(mut self, limit: i32) -> Self {
+        self.backoff_limit = Some(limit);
+        self
+    }
+    /// Set the wall-clock deadline, in seconds, after which the job is terminated
+    pub fn active_deadline_seconds(mut self, seconds: i64) -> Self {
+        self.active_deadline_seconds = Some(seconds);
+        self
+    }
+    /// Set the completion mode.
+    pub fn completion_mode(mut self, mode: CompletionMode) -> Self {
+        self.completion_mode = Some(mode);
+        self
+    }
+    /// Garbage-collect the job this many seconds after it finishes
+    pub fn ttl_seconds_after_finished(mut self, seconds: i32) -> Self {
+        self.ttl_seconds_after_finished = Some(seconds);
+        self
+    }
     pub fn to_job(self) -> batchapi::Job {
         batchapi::Job {
             // TODO: Could make this generic.
@@ -91,8 +381,12 @@ impl JobBuilder {
                 ..Default::default()
             }),
             spec: Some(batchapi::JobSpec {
-                backoff_limit: Some(4),
+                backoff_limit: self.backoff_limit,
                 parallelism: self.parallelism,
+                completions: self.completions,
+                active_deadline_seconds: self.active_deadline_seconds,
+                completion_mode: self.completion_mode.map(|mode| mode.as_str().to_string()),
+                ttl_seconds_after_finished: self.ttl_seconds_after_finished,
                 template: api::PodTemplateSpec {
                     metadata: Some(meta::ObjectMeta {
                         name: Some(self.name.clone()),
@@ -100,32 +394,41 @@ impl JobBuilder {
                         owner_references: self.owner_ref.clone(),
                         ..Default::default()
                     }),
-                    spec: Some(
+                    spec: Some(with_probes(
                         self.component
                             .to_pod_spec_with_policy(self.restart_policy.clone()),
-                    ),
+                        self.liveness_probe.clone(),
+                        self.readiness_probe.clone(),
+                    )),
                 },
                 ..Default::default()
             }),
             ..Default::default()
         }
     }
-    pub fn do_request(self, client: APIClient, namespace: String) -> InstigatorResult {
-        let job = self.to_job();
-        let pp = kube::api::PostParams::default();
-        // Right now, the Batch API is not transparent through Kube.
-        // Next release of Kube will fix this
-        let batch = kube::api::RawApi {
-            group: "batch".into(),
-            resource: "jobs".into(),
-            prefix: "apis".into(),
-            namespace: Some(namespace),
-            version: "v1".into(),
+    pub async fn do_request(self, client: APIClient, namespace: String) -> InstigatorResult {
+        let name = self.name.clone();
+        let limiter = self.concurrency_limiter.clone();
+        let target_node = self.target_node.clone();
+        let _permit = match &limiter {
+            Some(limiter) => Some(
+                limiter
+                    .acquire(target_node.as_deref().unwrap_or(UNSCHEDULED_NODE))
+                    .await,
+            ),
+            None => None,
         };
 
-        let req = batch.create(&pp, serde_json::to_vec(&job)?)?;
-        client.request::<batchapi::Job>(req)?;
-        Ok(())
+        let job = self.to_job();
+        let jobs = kube::api::Api::<batchapi::Job>::namespaced(client, &namespace);
+        create_or_patch(&jobs, &name, &job).await?;
+        if limiter.is_some() {
+            // Hold the permit until the job's pod has actually started, not
+            // just until the API call above returns.
+            wait_for_job_started(&jobs, &name).await
+        } else {
+            Ok(())
+        }
     }
 }
 
@@ -134,6 +437,7 @@ pub struct ServiceBuilder {
     labels: Labels,
     name: String,
     owner_ref: Option<Vec<meta::OwnerReference>>,
+    cluster_ip: Option<String>,
 }
 
 impl ServiceBuilder {
@@ -143,6 +447,7 @@ impl ServiceBuilder {
             component: component,
             labels: Labels::new(),
             owner_ref: None,
+            cluster_ip: None,
         }
     }
     pub fn labels(mut self, labels: Labels) -> Self {
@@ -153,33 +458,48 @@ impl ServiceBuilder {
         self.owner_ref = owner_ref;
         self
     }
+    /// Set the cluster IP for the service.
+    ///
+    /// Passing `"None"` produces a headless service, which is what gives a
+    /// StatefulSet's pods their stable per-replica DNS names.
+    pub fn cluster_ip(mut self, cluster_ip: String) -> Self {
+        self.cluster_ip = Some(cluster_ip);
+        self
+    }
     pub fn to_service(self) -> Option<api::Service> {
-        self.component.clone().listening_port().and_then(|port| {
-            Some(api::Service {
-                metadata: Some(meta::ObjectMeta {
-                    name: Some(self.name.clone()),
-                    labels: Some(self.labels.clone()),
-                    owner_references: self.owner_ref.clone(),
-                    ..Default::default()
-                }),
-                spec: Some(api::ServiceSpec {
-                    selector: Some(self.labels),
-                    ports: Some(vec![port.to_service_port()]),
-                    ..Default::default()
-                }),
+        let ports: Vec<api::ServicePort> = self
+            .component
+            .clone()
+            .listening_ports()
+            .into_iter()
+            .map(|port| port.to_service_port())
+            .collect();
+        if ports.is_empty() {
+            return None;
+        }
+        Some(api::Service {
+            metadata: Some(meta::ObjectMeta {
+                name: Some(self.name.clone()),
+                labels: Some(self.labels.clone()),
+                owner_references: self.owner_ref.clone(),
                 ..Default::default()
-            })
+            }),
+            spec: Some(api::ServiceSpec {
+                selector: Some(self.labels),
+                ports: Some(ports),
+                cluster_ip: self.cluster_ip,
+                ..Default::default()
+            }),
+            ..Default::default()
         })
     }
-    pub fn do_request(self, client: APIClient, namespace: String) -> InstigatorResult {
+    pub async fn do_request(self, client: APIClient, namespace: String) -> InstigatorResult {
+        let name = self.name.clone();
         match self.to_service() {
             Some(svc) => {
                 info!("Service:\n{}", serde_json::to_string_pretty(&svc).unwrap());
-                let pp = PostParams::default();
-                kube::api::Api::v1Service(client)
-                    .within(namespace.as_str())
-                    .create(&pp, serde_json::to_vec(&svc)?)?;
-                Ok(())
+                let services = kube::api::Api::<api::Service>::namespaced(client, &namespace);
+                create_or_patch(&services, &name, &svc).await
             }
             // No service to create
             None => {
@@ -189,3 +509,312 @@ impl ServiceBuilder {
         }
     }
 }
+
+/// StatefulSetBuilder builds a StatefulSet for workloads that need stable
+/// network identity and per-replica persistent storage, such as Scylla.
+///
+/// Unlike JobBuilder and bare pods, a StatefulSet requires a companion
+/// headless service to hand out its pods' stable DNS names, so `do_request`
+/// creates that service alongside the StatefulSet itself. The service comes
+/// from `ServiceBuilder`, so it is only created when the component declares at
+/// least one listening port; otherwise no headless service is created, although
+/// the StatefulSet's `serviceName` is still set to the instance name.
+pub(crate) struct StatefulSetBuilder {
+    component: Component,
+    labels: Labels,
+    name: String,
+    owner_ref: Option<Vec<meta::OwnerReference>>,
+    replicas: Option<i32>,
+    liveness_probe: Option<api::Probe>,
+    readiness_probe: Option<api::Probe>,
+    concurrency_limiter: Option<NodeConcurrencyLimiter>,
+    target_node: Option<String>,
+}
+
+impl StatefulSetBuilder {
+    /// Create a StatefulSetBuilder
+    ///
+    /// Liveness and readiness probes default to whatever the component's
+    /// health-check definition translates to, and can be overridden with
+    /// `liveness_probe`/`readiness_probe`.
+    pub fn new(instance_name: String, component: Component) -> Self {
+        let liveness_probe = component.liveness_probe();
+        let readiness_probe = component.readiness_probe();
+        StatefulSetBuilder {
+            name: instance_name,
+            component: component,
+            labels: Labels::new(),
+            owner_ref: None,
+            replicas: None,
+            liveness_probe,
+            readiness_probe,
+            concurrency_limiter: None,
+            target_node: None,
+        }
+    }
+    /// Add labels
+    pub fn labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+    /// Set the owner reference for the StatefulSet, its headless service and the pod
+    pub fn owner_ref(mut self, owner: Option<Vec<meta::OwnerReference>>) -> Self {
+        self.owner_ref = owner;
+        self
+    }
+    /// Set the replica count
+    pub fn replicas(mut self, count: i32) -> Self {
+        self.replicas = Some(count);
+        self
+    }
+    /// Gate this StatefulSet's instantiation against `target_node`'s concurrency limit
+    pub fn concurrency_limiter(
+        mut self,
+        limiter: NodeConcurrencyLimiter,
+        target_node: String,
+    ) -> Self {
+        self.concurrency_limiter = Some(limiter);
+        self.target_node = Some(target_node);
+        self
+    }
+    /// Override the liveness probe attached to each replica's container
+    pub fn liveness_probe(mut self, probe: Option<api::Probe>) -> Self {
+        self.liveness_probe = probe;
+        self
+    }
+    /// Override the readiness probe attached to each replica's container
+    ///
+    /// This is what keeps the headless service from routing traffic to a
+    /// replica that hasn't finished bootstrapping yet.
+    pub fn readiness_probe(mut self, probe: Option<api::Probe>) -> Self {
+        self.readiness_probe = probe;
+        self
+    }
+    /// Build the headless service that backs this StatefulSet's network identity.
+    fn to_headless_service(&self) -> Option<api::Service> {
+        ServiceBuilder::new(self.name.clone(), self.component.clone())
+            .labels(self.labels.clone())
+            .owner_reference(self.owner_ref.clone())
+            .cluster_ip("None".to_string())
+            .to_service()
+    }
+    pub fn to_stateful_set(self) -> apps::StatefulSet {
+        let claims: Vec<api::PersistentVolumeClaim> = self
+            .component
+            .clone()
+            .storage_volumes()
+            .into_iter()
+            .map(|volume| api::PersistentVolumeClaim {
+                metadata: Some(meta::ObjectMeta {
+                    name: Some(volume.name.clone()),
+                    labels: Some(self.labels.clone()),
+                    ..Default::default()
+                }),
+                spec: Some(volume.to_claim_spec()),
+                ..Default::default()
+            })
+            .collect();
+
+        apps::StatefulSet {
+            metadata: Some(meta::ObjectMeta {
+                name: Some(self.name.clone()),
+                labels: Some(self.labels.clone()),
+                owner_references: self.owner_ref.clone(),
+                ..Default::default()
+            }),
+            spec: Some(apps::StatefulSetSpec {
+                service_name: self.name.clone(),
+                replicas: self.replicas,
+                selector: meta::LabelSelector {
+                    match_labels: Some(self.labels.clone()),
+                    ..Default::default()
+                },
+                template: api::PodTemplateSpec {
+                    metadata: Some(meta::ObjectMeta {
+                        name: Some(self.name.clone()),
+                        labels: Some(self.labels.clone()),
+                        owner_references: self.owner_ref.clone(),
+                        ..Default::default()
+                    }),
+                    spec: Some(with_probes(
+                        self.component
+                            .clone()
+                            .to_pod_spec_with_policy("Always".to_string()),
+                        self.liveness_probe.clone(),
+                        self.readiness_probe.clone(),
+                    )),
+                },
+                volume_claim_templates: if claims.is_empty() {
+                    None
+                } else {
+                    Some(claims)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+    pub async fn do_request(self, client: APIClient, namespace: String) -> InstigatorResult {
+        let name = self.name.clone();
+        let limiter = self.concurrency_limiter.clone();
+        let target_node = self.target_node.clone();
+        let _permit = match &limiter {
+            Some(limiter) => Some(
+                limiter
+                    .acquire(target_node.as_deref().unwrap_or(UNSCHEDULED_NODE))
+                    .await,
+            ),
+            None => None,
+        };
+
+        if let Some(headless) = self.to_headless_service() {
+            info!(
+                "Headless service:\n{}",
+                serde_json::to_string_pretty(&headless).unwrap()
+            );
+            let services = kube::api::Api::<api::Service>::namespaced(client.clone(), &namespace);
+            create_or_patch(&services, &name, &headless).await?;
+        }
+
+        let desired_replicas = self.replicas.unwrap_or(1);
+        let stateful_set = self.to_stateful_set();
+        let stateful_sets = kube::api::Api::<apps::StatefulSet>::namespaced(client, &namespace);
+        create_or_patch(&stateful_sets, &name, &stateful_set).await?;
+        if limiter.is_some() {
+            // Hold the permit until the desired number of replicas are
+            // actually ready, not just until the API call above returns.
+            wait_for_stateful_set_ready(&stateful_sets, &name, desired_replicas).await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schematic::component::StorageVolume;
+
+    fn test_component(storage_volumes: Vec<StorageVolume>) -> Component {
+        Component {
+            name: "scylla".to_string(),
+            image: "scylladb/scylla:5.2".to_string(),
+            listening_ports: Vec::new(),
+            storage_volumes,
+            liveness_check: None,
+            readiness_check: None,
+        }
+    }
+
+    #[test]
+    fn to_stateful_set_builds_a_volume_claim_template_per_storage_volume() {
+        let component = test_component(vec![StorageVolume {
+            name: "data".to_string(),
+            size: "10Gi".to_string(),
+            access_modes: vec!["ReadWriteOnce".to_string()],
+        }]);
+        let stateful_set = StatefulSetBuilder::new("scylla".to_string(), component)
+            .replicas(3)
+            .to_stateful_set();
+
+        let spec = stateful_set.spec.expect("expected a StatefulSetSpec");
+        assert_eq!(spec.replicas, Some(3));
+        let claims = spec
+            .volume_claim_templates
+            .expect("expected volume claim templates");
+        assert_eq!(claims.len(), 1);
+        assert_eq!(
+            claims[0].metadata.as_ref().and_then(|m| m.name.clone()),
+            Some("data".to_string())
+        );
+        let requests = claims[0]
+            .spec
+            .as_ref()
+            .and_then(|s| s.resources.as_ref())
+            .and_then(|r| r.requests.as_ref())
+            .expect("expected resource requests");
+        assert_eq!(
+            requests.get("storage"),
+            Some(&k8s_openapi::apimachinery::pkg::api::resource::Quantity(
+                "10Gi".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn to_job_threads_lifecycle_settings_into_the_job_spec() {
+        let component = test_component(Vec::new());
+        let job = JobBuilder::new("scylla-job".to_string(), component)
+            .parallelism(3)
+            .completions(3)
+            .backoff_limit(2)
+            .active_deadline_seconds(600)
+            .completion_mode(CompletionMode::Indexed)
+            .ttl_seconds_after_finished(120)
+            .to_job();
+
+        let spec = job.spec.expect("expected a JobSpec");
+        assert_eq!(spec.parallelism, Some(3));
+        assert_eq!(spec.completions, Some(3));
+        assert_eq!(spec.backoff_limit, Some(2));
+        assert_eq!(spec.active_deadline_seconds, Some(600));
+        assert_eq!(spec.completion_mode, Some("Indexed".to_string()));
+        assert_eq!(spec.ttl_seconds_after_finished, Some(120));
+    }
+
+    #[test]
+    fn decide_sync_action_creates_when_nothing_exists_yet() {
+        let existing: Option<batchapi::Job> = None;
+        assert_eq!(decide_sync_action(&existing), SyncAction::Create);
+    }
+
+    #[test]
+    fn decide_sync_action_patches_when_the_object_already_exists() {
+        let existing = Some(batchapi::Job::default());
+        assert_eq!(decide_sync_action(&existing), SyncAction::Patch);
+    }
+
+    #[tokio::test]
+    async fn node_concurrency_limiter_blocks_at_the_configured_limit() {
+        let limiter = NodeConcurrencyLimiter::new(1);
+        let first = limiter.acquire("node-a").await;
+
+        // The slot is already taken, so a second acquire against the same
+        // node must not resolve while the first permit is still held.
+        let second =
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire("node-a")).await;
+        assert!(second.is_err(), "acquire should have blocked at the limit");
+
+        drop(first);
+
+        // Releasing the first permit frees the slot back up.
+        let third =
+            tokio::time::timeout(Duration::from_millis(200), limiter.acquire("node-a")).await;
+        assert!(
+            third.is_ok(),
+            "acquire should succeed once the held permit is dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn node_concurrency_limiter_tracks_nodes_independently() {
+        let limiter = NodeConcurrencyLimiter::new(1);
+        let _a = limiter.acquire("node-a").await;
+
+        // node-b has its own slot, so it shouldn't be blocked by node-a's.
+        let on_other_node =
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire("node-b")).await;
+        assert!(
+            on_other_node.is_ok(),
+            "a different node should have its own concurrency slot"
+        );
+    }
+
+    #[tokio::test]
+    async fn node_concurrency_limiter_pauses_instantiation_when_limit_is_zero() {
+        let limiter = NodeConcurrencyLimiter::new(0);
+        let acquired =
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire("node-a")).await;
+        assert!(acquired.is_err(), "a limit of 0 should pause acquisition");
+    }
+}